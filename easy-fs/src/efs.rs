@@ -0,0 +1,195 @@
+use super::{
+    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
+    SuperBlock, BLOCK_SZ,
+};
+use alloc::sync::Arc;
+use spin::Mutex;
+///An easy file system on block
+pub struct EasyFileSystem {
+    ///Real device
+    pub block_device: Arc<dyn BlockDevice>,
+    ///Inode bitmap
+    pub inode_bitmap: Bitmap,
+    ///Data bitmap
+    pub data_bitmap: Bitmap,
+    inode_area_start_block: u32,
+    data_area_start_block: u32,
+    /// total number of blocks in the filesystem
+    total_blocks: u32,
+    /// free inodes, kept in step with the inode allocator
+    free_inodes: u32,
+    /// free data blocks, kept in step with the data allocator
+    free_data_blocks: u32,
+}
+
+type DataBlock = [u8; BLOCK_SZ];
+/// An easy fs over a block device
+impl EasyFileSystem {
+    /// Create a fresh filesystem on a block device
+    pub fn create(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+    ) -> Arc<Mutex<Self>> {
+        // calculate block size of areas & create bitmaps
+        let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
+        let inode_num = inode_bitmap.maximum();
+        let inode_area_blocks =
+            ((inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SZ - 1) / BLOCK_SZ) as u32;
+        let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
+        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
+        let data_bitmap_blocks = (data_total_blocks + 4096) / 4097;
+        let data_area_blocks = data_total_blocks - data_bitmap_blocks;
+        let data_bitmap = Bitmap::new(
+            (1 + inode_bitmap_blocks + inode_area_blocks) as usize,
+            data_bitmap_blocks as usize,
+        );
+        let mut efs = Self {
+            block_device: Arc::clone(&block_device),
+            inode_bitmap,
+            data_bitmap,
+            inode_area_start_block: 1 + inode_bitmap_blocks,
+            data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            total_blocks,
+            free_inodes: inode_num as u32,
+            free_data_blocks: data_area_blocks,
+        };
+        // clear all blocks
+        for i in 0..total_blocks {
+            get_block_cache(i as usize, Arc::clone(&block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| {
+                    for byte in data_block.iter_mut() {
+                        *byte = 0;
+                    }
+                });
+        }
+        // initialize SuperBlock
+        get_block_cache(0, Arc::clone(&block_device)).lock().modify(
+            0,
+            |super_block: &mut SuperBlock| {
+                super_block.initialize(
+                    total_blocks,
+                    inode_bitmap_blocks,
+                    inode_area_blocks,
+                    data_bitmap_blocks,
+                    data_area_blocks,
+                );
+            },
+        );
+        // write back immediately
+        // create a inode for root node "/"
+        assert_eq!(efs.alloc_inode(), 0);
+        let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
+        get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
+            .lock()
+            .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
+                disk_inode.initialize(0, DiskInodeType::Dir);
+                // The root directory has no parent to link it and never passes
+                // through `append_dirent`, so give it the nlink a directory
+                // carries for itself and its own "." (the 2 a fresh `mkdir`
+                // produces).
+                disk_inode.link_cnt = 2;
+            });
+        block_cache_sync_all();
+        Arc::new(Mutex::new(efs))
+    }
+    /// Open a block device as a filesystem
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+        // read SuperBlock
+        get_block_cache(0, Arc::clone(&block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| {
+                assert!(super_block.is_valid(), "Error loading EFS!");
+                let inode_total_blocks =
+                    super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
+                let inode_bitmap = Bitmap::new(1, super_block.inode_bitmap_blocks as usize);
+                let data_bitmap = Bitmap::new(
+                    (1 + inode_total_blocks) as usize,
+                    super_block.data_bitmap_blocks as usize,
+                );
+                // seed the free counters from the persisted bitmaps
+                let used_inodes = inode_bitmap.count_allocated(&block_device) as u32;
+                let used_data = data_bitmap.count_allocated(&block_device) as u32;
+                let efs = Self {
+                    inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
+                    data_area_start_block: 1
+                        + inode_total_blocks
+                        + super_block.data_bitmap_blocks,
+                    total_blocks: super_block.total_blocks,
+                    free_inodes: inode_bitmap.maximum() as u32 - used_inodes,
+                    free_data_blocks: super_block.data_area_blocks - used_data,
+                    inode_bitmap,
+                    data_bitmap,
+                    block_device,
+                };
+                Arc::new(Mutex::new(efs))
+            })
+    }
+    /// Get the root inode of the filesystem
+    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
+        let block_device = Arc::clone(&efs.lock().block_device);
+        // acquire efs lock temporarily
+        let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
+        // release efs lock
+        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+    }
+    /// Get inode by id
+    pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
+        let inode_size = core::mem::size_of::<DiskInode>();
+        let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
+        let block_id = self.inode_area_start_block + inode_id / inodes_per_block;
+        (
+            block_id,
+            (inode_id % inodes_per_block) as usize * inode_size,
+        )
+    }
+    /// Get data block by id
+    pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
+        self.data_area_start_block + data_block_id
+    }
+    /// Allocate a new inode
+    pub fn alloc_inode(&mut self) -> u32 {
+        let inode_id = self.inode_bitmap.alloc(&self.block_device).unwrap() as u32;
+        self.free_inodes -= 1;
+        inode_id
+    }
+
+    /// Allocate a data block
+    pub fn alloc_data(&mut self) -> u32 {
+        let block_id = self.data_bitmap.alloc(&self.block_device).unwrap() as u32;
+        self.free_data_blocks -= 1;
+        block_id + self.data_area_start_block
+    }
+    /// Deallocate a data block
+    pub fn dealloc_data(&mut self, block_id: u32) {
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |data_block: &mut DataBlock| {
+                data_block.iter_mut().for_each(|p| {
+                    *p = 0;
+                })
+            });
+        self.data_bitmap.dealloc(
+            &self.block_device,
+            (block_id - self.data_area_start_block) as usize,
+        );
+        self.free_data_blocks += 1;
+    }
+    /// Deallocate an inode (after its blocks are cleared)
+    pub fn dealloc_disk_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize);
+        self.free_inodes += 1;
+    }
+    /// Report filesystem usage for a statfs-style query.
+    pub fn stat_fs(&self) -> crate::StatFs {
+        crate::StatFs {
+            total_blocks: self.total_blocks as u64,
+            free_blocks: self.free_data_blocks as u64,
+            total_inodes: self.inode_bitmap.maximum() as u64,
+            free_inodes: self.free_inodes as u64,
+            block_size: BLOCK_SZ as u32,
+        }
+    }
+}
@@ -0,0 +1,591 @@
+use super::{get_block_cache, BlockDevice, BLOCK_SZ};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter, Result};
+
+/// Magic number for sanity check
+const EFS_MAGIC: u32 = 0x3b80_0001;
+/// The max number of direct inodes
+const INODE_DIRECT_COUNT: usize = 28;
+/// The max length of inode name
+const NAME_LENGTH_LIMIT: usize = 27;
+/// The max number of indirect1 inodes
+const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
+/// The max number of indirect2 inodes
+const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
+/// The upper bound of direct inode index
+const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
+/// The upper bound of indirect1 inode index
+const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
+
+/// Super block of a filesystem
+#[repr(C)]
+pub struct SuperBlock {
+    magic: u32,
+    /// total number of blocks
+    pub total_blocks: u32,
+    pub inode_bitmap_blocks: u32,
+    pub inode_area_blocks: u32,
+    pub data_bitmap_blocks: u32,
+    pub data_area_blocks: u32,
+}
+
+impl Debug for SuperBlock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("SuperBlock")
+            .field("total_blocks", &self.total_blocks)
+            .field("inode_bitmap_blocks", &self.inode_bitmap_blocks)
+            .field("inode_area_blocks", &self.inode_area_blocks)
+            .field("data_bitmap_blocks", &self.data_bitmap_blocks)
+            .field("data_area_blocks", &self.data_area_blocks)
+            .finish()
+    }
+}
+
+impl SuperBlock {
+    /// Initialize a super block
+    pub fn initialize(
+        &mut self,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        inode_area_blocks: u32,
+        data_bitmap_blocks: u32,
+        data_area_blocks: u32,
+    ) {
+        *self = Self {
+            magic: EFS_MAGIC,
+            total_blocks,
+            inode_bitmap_blocks,
+            inode_area_blocks,
+            data_bitmap_blocks,
+            data_area_blocks,
+        }
+    }
+    /// Check if a super block is valid using efs magic
+    pub fn is_valid(&self) -> bool {
+        self.magic == EFS_MAGIC
+    }
+}
+
+/// Type of a disk inode
+#[derive(PartialEq, Clone, Copy)]
+pub enum DiskInodeType {
+    /// a regular file
+    File,
+    /// a directory
+    Dir,
+    /// a symbolic link; its data block holds the target path
+    Symlink,
+}
+
+/// A indirect block
+type IndirectBlock = [u32; BLOCK_SZ / 4];
+/// A data block
+type DataBlock = [u8; BLOCK_SZ];
+
+/// A disk inode.
+///
+/// Besides the block pointers and `size`, it carries the inode `id`, hard
+/// link count, file type and the POSIX ownership/permission triple
+/// (`uid`/`gid`/`mode`, the low 12 bits of `mode` being the permission and
+/// special bits).
+#[repr(C)]
+pub struct DiskInode {
+    /// inode id
+    pub id: u32,
+    /// total data size in bytes
+    pub size: u32,
+    /// number of hard links
+    pub link_cnt: u32,
+    /// owner user id
+    pub uid: u32,
+    /// owner group id
+    pub gid: u32,
+    /// permission and special bits (low 12 bits)
+    pub mode: u32,
+    /// last access time (seconds)
+    pub atime: u64,
+    /// last modification time (seconds)
+    pub mtime: u64,
+    /// last status-change time (seconds)
+    pub ctime: u64,
+    /// block holding the bucket table (bucket id -> block id), 0 if no index
+    index_block: u32,
+    /// number of buckets in the directory hash index (0 = no index)
+    bucket_count: u32,
+    direct: [u32; INODE_DIRECT_COUNT],
+    indirect1: u32,
+    indirect2: u32,
+    type_: DiskInodeType,
+}
+
+impl DiskInode {
+    /// Initialize a disk inode, as well as all direct inodes under it.
+    ///
+    /// New inodes start unlinked (`link_cnt == 0`); the name `append_dirent`
+    /// adds raises it to 1. They are owned by root with the default mode for
+    /// their type (`0o755` directories, `0o644` files).
+    pub fn initialize(&mut self, id: u32, type_: DiskInodeType) {
+        self.id = id;
+        self.size = 0;
+        self.link_cnt = 0;
+        self.uid = 0;
+        self.gid = 0;
+        self.mode = match type_ {
+            DiskInodeType::Dir => 0o755,
+            DiskInodeType::File => 0o644,
+            DiskInodeType::Symlink => 0o777,
+        };
+        self.atime = 0;
+        self.mtime = 0;
+        self.ctime = 0;
+        self.index_block = 0;
+        self.bucket_count = 0;
+        self.direct.iter_mut().for_each(|v| *v = 0);
+        self.indirect1 = 0;
+        self.indirect2 = 0;
+        self.type_ = type_;
+    }
+    /// Whether this inode is a directory
+    pub fn is_dir(&self) -> bool {
+        self.type_ == DiskInodeType::Dir
+    }
+    /// Whether this inode is a file
+    pub fn is_file(&self) -> bool {
+        self.type_ == DiskInodeType::File
+    }
+    /// Whether this inode is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        self.type_ == DiskInodeType::Symlink
+    }
+    /// Return block number correspond to size.
+    pub fn data_blocks(&self) -> u32 {
+        Self::_data_blocks(self.size)
+    }
+    fn _data_blocks(size: u32) -> u32 {
+        (size + BLOCK_SZ as u32 - 1) / BLOCK_SZ as u32
+    }
+    /// Return number of blocks needed including indirect1/2.
+    pub fn total_blocks(size: u32) -> u32 {
+        let data_blocks = Self::_data_blocks(size) as usize;
+        let mut total = data_blocks;
+        // indirect1
+        if data_blocks > INODE_DIRECT_COUNT {
+            total += 1;
+        }
+        // indirect2
+        if data_blocks > INDIRECT1_BOUND {
+            total += 1;
+            // sub indirect1
+            total +=
+                (data_blocks - INDIRECT1_BOUND + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+        }
+        total as u32
+    }
+    /// Get the number of data blocks that have to be allocated given the new size of data
+    pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
+        assert!(new_size >= self.size);
+        Self::total_blocks(new_size) - Self::total_blocks(self.size)
+    }
+    /// Get id of block given inner id
+    pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        let inner_id = inner_id as usize;
+        if inner_id < INODE_DIRECT_COUNT {
+            self.direct[inner_id]
+        } else if inner_id < INDIRECT1_BOUND {
+            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect_block: &IndirectBlock| {
+                    indirect_block[inner_id - INODE_DIRECT_COUNT]
+                })
+        } else {
+            let last = inner_id - INDIRECT1_BOUND;
+            let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| {
+                    indirect2[last / INODE_INDIRECT1_COUNT]
+                });
+            get_block_cache(indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect1: &IndirectBlock| {
+                    indirect1[last % INODE_INDIRECT1_COUNT]
+                })
+        }
+    }
+    /// Inncrease the size of current disk inode
+    pub fn increase_size(
+        &mut self,
+        new_size: u32,
+        new_blocks: Vec<u32>,
+        block_device: &Arc<dyn BlockDevice>,
+    ) {
+        let mut current_blocks = self.data_blocks();
+        self.size = new_size;
+        let mut total_blocks = self.data_blocks();
+        let mut new_blocks = new_blocks.into_iter();
+        // fill direct
+        while current_blocks < total_blocks.min(INODE_DIRECT_COUNT as u32) {
+            self.direct[current_blocks as usize] = new_blocks.next().unwrap();
+            current_blocks += 1;
+        }
+        // alloc indirect1
+        if total_blocks > INODE_DIRECT_COUNT as u32 {
+            if current_blocks == INODE_DIRECT_COUNT as u32 {
+                self.indirect1 = new_blocks.next().unwrap();
+            }
+            current_blocks -= INODE_DIRECT_COUNT as u32;
+            total_blocks -= INODE_DIRECT_COUNT as u32;
+        } else {
+            return;
+        }
+        // fill indirect1
+        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect1: &mut IndirectBlock| {
+                while current_blocks < total_blocks.min(INODE_INDIRECT1_COUNT as u32) {
+                    indirect1[current_blocks as usize] = new_blocks.next().unwrap();
+                    current_blocks += 1;
+                }
+            });
+        // alloc indirect2
+        if total_blocks > INODE_INDIRECT1_COUNT as u32 {
+            if current_blocks == INODE_INDIRECT1_COUNT as u32 {
+                self.indirect2 = new_blocks.next().unwrap();
+            }
+            current_blocks -= INODE_INDIRECT1_COUNT as u32;
+            total_blocks -= INODE_INDIRECT1_COUNT as u32;
+        } else {
+            return;
+        }
+        // fill indirect2 from (a0, b0) -> (a1, b1)
+        let mut a0 = current_blocks as usize / INODE_INDIRECT1_COUNT;
+        let mut b0 = current_blocks as usize % INODE_INDIRECT1_COUNT;
+        let a1 = total_blocks as usize / INODE_INDIRECT1_COUNT;
+        let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
+        // alloc low-level indirect1
+        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect2: &mut IndirectBlock| {
+                while (a0 < a1) || (a0 == a1 && b0 < b1) {
+                    if b0 == 0 {
+                        indirect2[a0] = new_blocks.next().unwrap();
+                    }
+                    // fill current
+                    get_block_cache(indirect2[a0] as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect1: &mut IndirectBlock| {
+                            indirect1[b0] = new_blocks.next().unwrap();
+                        });
+                    // move to next
+                    b0 += 1;
+                    if b0 == INODE_INDIRECT1_COUNT {
+                        b0 = 0;
+                        a0 += 1;
+                    }
+                }
+            });
+    }
+
+    /// Clear size to zero and return blocks that should be deallocated.
+    /// We will clear the block contents to zero later.
+    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        let mut v: Vec<u32> = Vec::new();
+        let mut data_blocks = self.data_blocks() as usize;
+        self.size = 0;
+        let mut current_blocks = 0usize;
+        // direct
+        while current_blocks < data_blocks.min(INODE_DIRECT_COUNT) {
+            v.push(self.direct[current_blocks]);
+            self.direct[current_blocks] = 0;
+            current_blocks += 1;
+        }
+        // indirect1 block
+        if data_blocks > INODE_DIRECT_COUNT {
+            v.push(self.indirect1);
+            data_blocks -= INODE_DIRECT_COUNT;
+            current_blocks = 0;
+        } else {
+            return v;
+        }
+        // indirect1
+        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect1: &mut IndirectBlock| {
+                while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
+                    v.push(indirect1[current_blocks]);
+                    current_blocks += 1;
+                }
+            });
+        self.indirect1 = 0;
+        // indirect2 block
+        if data_blocks > INODE_INDIRECT1_COUNT {
+            v.push(self.indirect2);
+            data_blocks -= INODE_INDIRECT1_COUNT;
+        } else {
+            return v;
+        }
+        // indirect2
+        assert!(data_blocks <= INODE_INDIRECT2_COUNT);
+        let a1 = data_blocks / INODE_INDIRECT1_COUNT;
+        let b1 = data_blocks % INODE_INDIRECT1_COUNT;
+        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect2: &mut IndirectBlock| {
+                // full indirect1 blocks
+                for entry in indirect2.iter_mut().take(a1) {
+                    v.push(*entry);
+                    get_block_cache(*entry as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect1: &mut IndirectBlock| {
+                            for entry in indirect1.iter() {
+                                v.push(*entry);
+                            }
+                        });
+                }
+                // last indirect1 block
+                if b1 > 0 {
+                    v.push(indirect2[a1]);
+                    get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect1: &mut IndirectBlock| {
+                            for entry in indirect1.iter().take(b1) {
+                                v.push(*entry);
+                            }
+                        });
+                }
+            });
+        self.indirect2 = 0;
+        v
+    }
+    /// Read data from current disk inode
+    pub fn read_at(
+        &self,
+        offset: usize,
+        buf: &mut [u8],
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        let mut start = offset;
+        let end = (offset + buf.len()).min(self.size as usize);
+        if start >= end {
+            return 0;
+        }
+        let mut start_block = start / BLOCK_SZ;
+        let mut read_size = 0usize;
+        loop {
+            // calculate end of current block
+            let mut end_current_block = (start / BLOCK_SZ + 1) * BLOCK_SZ;
+            end_current_block = end_current_block.min(end);
+            // read and update read size
+            let block_read_size = end_current_block - start;
+            let dst = &mut buf[read_size..read_size + block_read_size];
+            get_block_cache(
+                self.get_block_id(start_block as u32, block_device) as usize,
+                Arc::clone(block_device),
+            )
+            .lock()
+            .read(0, |data_block: &DataBlock| {
+                let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
+                dst.copy_from_slice(src);
+            });
+            read_size += block_read_size;
+            // move to next block
+            if end_current_block == end {
+                break;
+            }
+            start_block += 1;
+            start = end_current_block;
+        }
+        read_size
+    }
+    /// Write data into current disk inode
+    /// size must be adjusted properly beforehand
+    pub fn write_at(
+        &mut self,
+        offset: usize,
+        buf: &[u8],
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        let mut start = offset;
+        let end = (offset + buf.len()).min(self.size as usize);
+        assert!(start <= end);
+        let mut start_block = start / BLOCK_SZ;
+        let mut write_size = 0usize;
+        loop {
+            // calculate end of current block
+            let mut end_current_block = (start / BLOCK_SZ + 1) * BLOCK_SZ;
+            end_current_block = end_current_block.min(end);
+            // write and update write size
+            let block_write_size = end_current_block - start;
+            get_block_cache(
+                self.get_block_id(start_block as u32, block_device) as usize,
+                Arc::clone(block_device),
+            )
+            .lock()
+            .modify(0, |data_block: &mut DataBlock| {
+                let src = &buf[write_size..write_size + block_write_size];
+                let dst = &mut data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_write_size];
+                dst.copy_from_slice(src);
+            });
+            write_size += block_write_size;
+            // move to next block
+            if end_current_block == end {
+                break;
+            }
+            start_block += 1;
+            start = end_current_block;
+        }
+        write_size
+    }
+    /// Number of buckets in the directory hash index (0 = no index yet).
+    pub fn dirent_buckets(&self) -> u32 {
+        self.bucket_count
+    }
+    /// Blocks required to back an index of `num_buckets` buckets: one table
+    /// block plus one block per bucket.
+    pub fn bucket_blocks_needed(num_buckets: u32) -> u32 {
+        num_buckets + 1
+    }
+    /// Block id backing `bucket`, read out of the index table block.
+    fn bucket_block_id(&self, bucket: usize, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        get_block_cache(self.index_block as usize, Arc::clone(block_device))
+            .lock()
+            .read(0, |table: &IndirectBlock| table[bucket])
+    }
+    /// Read bucket `bucket`'s block into `buf` (`buf.len()` bytes).
+    pub fn read_bucket(&self, bucket: usize, buf: &mut [u8], block_device: &Arc<dyn BlockDevice>) {
+        let block_id = self.bucket_block_id(bucket, block_device);
+        get_block_cache(block_id as usize, Arc::clone(block_device))
+            .lock()
+            .read(0, |data_block: &DataBlock| {
+                buf.copy_from_slice(&data_block[..buf.len()]);
+            });
+    }
+    /// Write `buf` back into bucket `bucket`'s block.
+    pub fn write_bucket(&self, bucket: usize, buf: &[u8], block_device: &Arc<dyn BlockDevice>) {
+        let block_id = self.bucket_block_id(bucket, block_device);
+        get_block_cache(block_id as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |data_block: &mut DataBlock| {
+                data_block[..buf.len()].copy_from_slice(buf);
+            });
+    }
+    /// Install a fresh, empty bucket table of `num_buckets` buckets backed by
+    /// `new_blocks` (exactly [`bucket_blocks_needed`] blocks: the table block
+    /// first, then one block per bucket). Returns the blocks of any previous
+    /// table so the caller can free them.
+    pub fn set_dirent_buckets(
+        &mut self,
+        num_buckets: u32,
+        new_blocks: Vec<u32>,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        assert!(num_buckets as usize <= INODE_INDIRECT1_COUNT);
+        let mut freed: Vec<u32> = Vec::new();
+        if self.index_block != 0 {
+            freed.push(self.index_block);
+            let old = self.bucket_count as usize;
+            get_block_cache(self.index_block as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |table: &IndirectBlock| {
+                    for &b in table.iter().take(old) {
+                        freed.push(b);
+                    }
+                });
+        }
+        let mut it = new_blocks.into_iter();
+        let index_block = it.next().unwrap();
+        get_block_cache(index_block as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |table: &mut IndirectBlock| {
+                for entry in table.iter_mut() {
+                    *entry = 0;
+                }
+                for entry in table.iter_mut().take(num_buckets as usize) {
+                    let b = it.next().unwrap();
+                    *entry = b;
+                    // start each bucket empty (len == 0)
+                    get_block_cache(b as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |data_block: &mut DataBlock| {
+                            for byte in data_block.iter_mut() {
+                                *byte = 0;
+                            }
+                        });
+                }
+            });
+        self.index_block = index_block;
+        self.bucket_count = num_buckets;
+        freed
+    }
+    /// Release the directory hash index entirely (the table block and every
+    /// bucket block), leaving the inode with no index. Returns the freed
+    /// blocks so the caller can hand them back to the allocator. A no-op when
+    /// there is no index.
+    pub fn clear_dirent_buckets(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        let mut freed: Vec<u32> = Vec::new();
+        if self.index_block != 0 {
+            freed.push(self.index_block);
+            let old = self.bucket_count as usize;
+            get_block_cache(self.index_block as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |table: &IndirectBlock| {
+                    for &b in table.iter().take(old) {
+                        freed.push(b);
+                    }
+                });
+        }
+        self.index_block = 0;
+        self.bucket_count = 0;
+        freed
+    }
+}
+
+/// A directory entry
+#[repr(C)]
+pub struct DirEntry {
+    name: [u8; NAME_LENGTH_LIMIT + 1],
+    inode_id: i32,
+}
+
+/// Size of a directory entry
+pub const DIRENT_SZ: usize = 32;
+
+impl DirEntry {
+    /// Create an empty directory entry
+    pub fn empty() -> Self {
+        Self {
+            name: [0u8; NAME_LENGTH_LIMIT + 1],
+            inode_id: 0,
+        }
+    }
+    /// Create a directory entry from name and inode id
+    pub fn new(name: &str, inode_id: i32) -> Self {
+        let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Self {
+            name: bytes,
+            inode_id,
+        }
+    }
+    /// Serialize into bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, DIRENT_SZ) }
+    }
+    /// Serialize into mutable bytes
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SZ) }
+    }
+    /// Get name of the entry
+    pub fn name(&self) -> &str {
+        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
+        core::str::from_utf8(&self.name[..len]).unwrap()
+    }
+    /// Get inode id of the entry
+    pub fn inode_id(&self) -> i32 {
+        self.inode_id
+    }
+    /// Whether the entry is a valid (non-tombstone) entry
+    pub fn is_valid(&self) -> bool {
+        self.inode_id >= 0
+    }
+}
@@ -0,0 +1,18 @@
+//!An easy file system isolated from the kernel
+#![no_std]
+extern crate alloc;
+mod bitmap;
+mod block_cache;
+mod block_dev;
+mod efs;
+mod layout;
+mod vfs;
+/// Use a block size of 512 bytes
+pub const BLOCK_SZ: usize = 512;
+use bitmap::Bitmap;
+use block_cache::{block_cache_sync_all, get_block_cache};
+pub use block_dev::BlockDevice;
+pub use efs::EasyFileSystem;
+use layout::{DiskInode, DiskInodeType, DirEntry, SuperBlock};
+pub use layout::DIRENT_SZ;
+pub use vfs::{set_time, Inode, Stat, StatFs, RENAME_EXCHANGE, RENAME_NOREPLACE};
@@ -5,7 +5,142 @@ use super::{
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use spin::{Mutex, MutexGuard};
+
+/// Wall clock injected by the kernel. Until [`set_time`] is called, easy-fs
+/// has no clock, so timestamps fall back to a monotonic counter that ticks
+/// once per reading — enough to order events.
+static CLOCK: AtomicU64 = AtomicU64::new(0);
+static CLOCK_SET: AtomicBool = AtomicBool::new(false);
+
+/// Inject the current time (seconds). Subsequent timestamp updates use it.
+pub fn set_time(secs: u64) {
+    CLOCK.store(secs, Ordering::SeqCst);
+    CLOCK_SET.store(true, Ordering::SeqCst);
+}
+
+/// Current time: the injected clock if set, else a monotonic counter.
+fn now() -> u64 {
+    if CLOCK_SET.load(Ordering::SeqCst) {
+        CLOCK.load(Ordering::SeqCst)
+    } else {
+        CLOCK.fetch_add(1, Ordering::SeqCst) + 1
+    }
+}
+
+/// A directory gains an on-disk hash index only once it reaches this many
+/// entries; below it a linear scan is cheaper than touching a bucket block.
+const DIR_HASH_MIN_ENTRIES: usize = 64;
+
+/// Average bucket chain length above which the bucket table is grown (and
+/// all entries rehashed) to keep lookups close to O(1).
+const DIR_HASH_LOAD_FACTOR: usize = 4;
+
+/// Number of `(name_hash, dirent_index)` pairs stored in one bucket block.
+pub const BUCKET_ENTRIES: usize = 63;
+
+/// FNV-1a hash of a directory entry name.
+fn hash_name(name: &str) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in name.as_bytes() {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// One hash bucket, laid out to fill a single block: a length followed by a
+/// short chain of `(name_hash, dirent_index)` pairs.
+#[repr(C)]
+pub struct DirBucket {
+    len: u32,
+    entries: [(u32, u32); BUCKET_ENTRIES],
+}
+
+impl DirBucket {
+    /// An empty bucket.
+    pub fn empty() -> Self {
+        Self {
+            len: 0,
+            entries: [(0, 0); BUCKET_ENTRIES],
+        }
+    }
+    /// Raw view for reading a bucket block off disk.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        let size = core::mem::size_of::<Self>();
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8, size) }
+    }
+    /// Raw view for writing a bucket block to disk.
+    pub fn as_bytes(&self) -> &[u8] {
+        let size = core::mem::size_of::<Self>();
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, size) }
+    }
+    /// Iterate the live `(name_hash, dirent_index)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = &(u32, u32)> {
+        self.entries[..self.len as usize].iter()
+    }
+    /// Whether the bucket holds the maximum [`BUCKET_ENTRIES`] pairs, so a
+    /// further `push` would be dropped and a lookup miss is inconclusive.
+    pub fn is_full(&self) -> bool {
+        self.len as usize >= BUCKET_ENTRIES
+    }
+    /// Append a pair; returns false when the bucket is full.
+    pub fn push(&mut self, name_hash: u32, idx: u32) -> bool {
+        if (self.len as usize) >= BUCKET_ENTRIES {
+            return false;
+        }
+        self.entries[self.len as usize] = (name_hash, idx);
+        self.len += 1;
+        true
+    }
+    /// Drop every pair pointing at `idx` (tombstone purge).
+    pub fn remove_idx(&mut self, idx: u32) {
+        let mut w = 0;
+        for r in 0..self.len as usize {
+            if self.entries[r].1 != idx {
+                self.entries[w] = self.entries[r];
+                w += 1;
+            }
+        }
+        self.len = w as u32;
+    }
+}
+
+/// Metadata snapshot returned by [`Inode::stat`].
+pub struct Stat {
+    /// file size in bytes
+    pub size: u32,
+    /// number of hard links
+    pub nlink: u32,
+    /// true if the inode is a directory
+    pub is_dir: bool,
+    /// last access time
+    pub atime: u64,
+    /// last modification time
+    pub mtime: u64,
+    /// last status-change time
+    pub ctime: u64,
+}
+
+/// Filesystem usage snapshot returned by [`Inode::stat_fs`].
+pub struct StatFs {
+    /// total number of blocks in the filesystem
+    pub total_blocks: u64,
+    /// free data blocks
+    pub free_blocks: u64,
+    /// total number of inodes
+    pub total_inodes: u64,
+    /// free inodes
+    pub free_inodes: u64,
+    /// block (and fragment) size in bytes
+    pub block_size: u32,
+}
+
+/// `rename` fails if the destination name already exists.
+pub const RENAME_NOREPLACE: u32 = 1;
+/// `rename` atomically exchanges the two names' target inodes.
+pub const RENAME_EXCHANGE: u32 = 2;
 /// Virtual filesystem layer over easy-fs
 pub struct Inode {
     block_id: usize,
@@ -44,6 +179,10 @@ impl Inode {
     fn is_file(&self) -> bool {
         self.read_disk_inode(|disk_inode| disk_inode.is_file())
     }
+    /// is symlink?
+    fn is_symlink_pri(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
     /// get size
     fn size(&self) -> u32 {
         self.read_disk_inode(|disk_inode| disk_inode.size as u32)
@@ -64,6 +203,12 @@ impl Inode {
                 for data_block in data_blocks_dealloc.into_iter() {
                     fs.dealloc_data(data_block);
                 }
+                // A directory may carry a hash index (table block + bucket
+                // blocks) that `clear_size` does not account for; release it
+                // too so tearing down a directory leaks nothing.
+                for index_block in disk_inode.clear_dirent_buckets(&self.block_device) {
+                    fs.dealloc_data(index_block);
+                }
             }
         });
         
@@ -81,6 +226,36 @@ impl Inode {
             disk_inode.link_cnt as usize
         })
     }
+    /// Update the selected timestamps to the current time.
+    ///
+    /// Must not be called from inside another `modify_disk_inode`/block-cache
+    /// borrow of this inode, or the block cache would deadlock.
+    fn touch(&self, atime: bool, mtime: bool, ctime: bool) {
+        let t = now();
+        self.modify_disk_inode(|disk_inode| {
+            if atime {
+                disk_inode.atime = t;
+            }
+            if mtime {
+                disk_inode.mtime = t;
+            }
+            if ctime {
+                disk_inode.ctime = t;
+            }
+        });
+    }
+    /// Read size, link count, type and timestamps in one locked read.
+    pub fn stat(&self) -> Stat {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| Stat {
+            size: disk_inode.size as u32,
+            nlink: disk_inode.link_cnt as u32,
+            is_dir: disk_inode.is_dir(),
+            atime: disk_inode.atime,
+            mtime: disk_inode.mtime,
+            ctime: disk_inode.ctime,
+        })
+    }
     /// Call a function over a disk inode to read it
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
@@ -93,10 +268,40 @@ impl Inode {
             .lock()
             .modify(self.block_offset, f)
     }
-    /// Find inode under a disk inode by name
+    /// Find inode under a disk inode by name.
+    ///
+    /// Small directories (below [`DIR_HASH_MIN_ENTRIES`] entries) are scanned
+    /// linearly to avoid index overhead. Once a directory has an on-disk hash
+    /// index (`disk_inode.dirent_buckets() > 0`), the name is hashed and only
+    /// the matching bucket block is read; full names are compared only on
+    /// hash collisions. The flat dirent array stays authoritative.
     fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
         // assert it is a directory
         assert!(disk_inode.is_dir());
+        let buckets = disk_inode.dirent_buckets() as usize;
+        if buckets > 0 {
+            let bucket = hash_name(name) as usize % buckets;
+            let mut entries = DirBucket::empty();
+            disk_inode.read_bucket(bucket, entries.as_bytes_mut(), &self.block_device);
+            for &(name_hash, idx) in entries.iter() {
+                if name_hash != hash_name(name) {
+                    continue;
+                }
+                let mut dirent = DirEntry::empty();
+                disk_inode.read_at(DIRENT_SZ * idx as usize, dirent.as_bytes_mut(), &self.block_device);
+                if dirent.is_valid() && dirent.name() == name {
+                    return Some(dirent.inode_id() as u32);
+                }
+            }
+            // A miss in a non-full bucket is authoritative: every live name
+            // hashing here is present, so the name does not exist.
+            if !entries.is_full() {
+                return None;
+            }
+            // A full bucket may have silently dropped an index entry, so its
+            // miss is inconclusive. Fall through to a linear scan of the
+            // dirent array, which always holds every live name.
+        }
         let file_count = (disk_inode.size as usize) / DIRENT_SZ;
         let mut dirent = DirEntry::empty();
         for i in 0..file_count {
@@ -104,25 +309,186 @@ impl Inode {
                 disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
                 DIRENT_SZ,
             );
-            if dirent.name() == name {
+            if dirent.is_valid() && dirent.name() == name {
                 return Some(dirent.inode_id() as u32);
             }
         }
         None
     }
-    /// Find inode under current inode by name
-    pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
+    /// Insert `(name, idx)` into the directory hash index, lazily creating it
+    /// once the directory reaches [`DIR_HASH_MIN_ENTRIES`] and rehashing
+    /// when the average chain length exceeds [`DIR_HASH_LOAD_FACTOR`].
+    fn bucket_insert(&self, name: &str, idx: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        let entries = (self.size() as usize) / DIRENT_SZ;
+        let buckets = self.read_disk_inode(|d| d.dirent_buckets() as usize);
+        if buckets == 0 {
+            if entries < DIR_HASH_MIN_ENTRIES {
+                return;
+            }
+            // cross the threshold: build the initial table from a full scan
+            self.rebuild_index((entries / DIR_HASH_LOAD_FACTOR).max(1), fs);
+            return;
+        }
+        if entries > buckets * DIR_HASH_LOAD_FACTOR {
+            self.rebuild_index(buckets * 2, fs);
+            return;
+        }
+        let bucket = hash_name(name) as usize % buckets;
+        self.modify_disk_inode(|disk_inode| {
+            let mut b = DirBucket::empty();
+            disk_inode.read_bucket(bucket, b.as_bytes_mut(), &self.block_device);
+            // A saturated bucket (e.g. many colliding hashes that doubling
+            // would not split) simply goes un-indexed: the name still lives in
+            // the authoritative dirent array and `find_inode_id` falls back to
+            // a linear scan for a full bucket, so no name is ever lost.
+            if b.push(hash_name(name), idx) {
+                disk_inode.write_bucket(bucket, b.as_bytes(), &self.block_device);
+            }
+        });
+    }
+    /// Remove every index entry pointing at slot `idx`.
+    fn bucket_purge(&self, name: &str, idx: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        let buckets = self.read_disk_inode(|d| d.dirent_buckets() as usize);
+        if buckets == 0 {
+            return;
+        }
+        let bucket = hash_name(name) as usize % buckets;
+        let was_full = self.modify_disk_inode(|disk_inode| {
+            let mut b = DirBucket::empty();
+            disk_inode.read_bucket(bucket, b.as_bytes_mut(), &self.block_device);
+            let was_full = b.is_full();
+            b.remove_idx(idx);
+            disk_inode.write_bucket(bucket, b.as_bytes(), &self.block_device);
+            was_full
+        });
+        // A full bucket may hold names it could not index; once it has room
+        // its misses become authoritative, so re-index every live entry.
+        if was_full {
+            self.rebuild_index(buckets, fs);
+        }
+    }
+    /// (Re)build the bucket table with `num_buckets` buckets by scanning the
+    /// authoritative dirent array and re-hashing every live entry. The backing
+    /// blocks are allocated from the fs up front; blocks of any prior table
+    /// are freed afterwards.
+    fn rebuild_index(&self, num_buckets: usize, fs: &mut MutexGuard<EasyFileSystem>) {
+        let count = (self.size() as usize) / DIRENT_SZ;
+        let blocks_needed = DiskInode::bucket_blocks_needed(num_buckets as u32);
+        let mut new_blocks: Vec<u32> = Vec::new();
+        for _ in 0..blocks_needed {
+            new_blocks.push(fs.alloc_data());
+        }
+        let freed = self.modify_disk_inode(|disk_inode| {
+            let freed = disk_inode.set_dirent_buckets(num_buckets as u32, new_blocks, &self.block_device);
+            let mut dirent = DirEntry::empty();
+            for i in 0..count {
+                disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+                if !dirent.is_valid() {
+                    continue;
+                }
+                let bucket = hash_name(dirent.name()) as usize % num_buckets;
+                let mut b = DirBucket::empty();
+                disk_inode.read_bucket(bucket, b.as_bytes_mut(), &self.block_device);
+                // If a bucket saturates during the rehash the overflow stays
+                // un-indexed; it is still reachable through the full-bucket
+                // linear fallback in `find_inode_id`.
+                b.push(hash_name(dirent.name()), i as u32);
+                disk_inode.write_bucket(bucket, b.as_bytes(), &self.block_device);
+            }
+            freed
+        });
+        for block in freed {
+            fs.dealloc_data(block);
+        }
+    }
+    /// 对 disk_inode 做三层权限检查：owner / group / other，root（uid 0）直接通过。
+    /// `want` 取 RWX 位（4/2/1），全部满足返回 true。
+    fn check_access_pri(&self, disk_inode: &DiskInode, uid: u32, gids: &[u32], want: u32) -> bool {
+        if uid == 0 {
+            return true;
+        }
+        let mode = disk_inode.mode;
+        let bits = if disk_inode.uid == uid {
+            (mode >> 6) & 0o7
+        } else if gids.iter().any(|g| *g == disk_inode.gid) {
+            (mode >> 3) & 0o7
+        } else {
+            mode & 0o7
+        };
+        bits & want == want
+    }
+    /// Check whether `(uid, gids)` may perform `want` (RWX bits) on this inode.
+    pub fn check_access(&self, uid: u32, gids: &[u32], want: u32) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| self.check_access_pri(disk_inode, uid, gids, want))
+    }
+    /// Change the permission bits of this inode (the low 12 mode bits).
+    pub fn chmod(&self, mode: u32) {
+        let _fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = (disk_inode.mode & !0o7777) | (mode & 0o7777);
+        });
+        self.touch(false, false, true);
+        block_cache_sync_all();
+    }
+    /// Change the owner uid/gid of this inode.
+    pub fn chown(&self, uid: u32, gid: u32) {
+        let _fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.uid = uid;
+            disk_inode.gid = gid;
+        });
+        block_cache_sync_all();
+    }
+    /// Maximum number of symlinks followed before giving up, to break loops.
+    const SYMLINK_MAX_DEPTH: usize = 40;
+    /// Build the child `Inode` at `inode_id`.
+    fn inode_at(&self, inode_id: u32, fs: &MutexGuard<EasyFileSystem>) -> Arc<Inode> {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        Arc::new(Self::new(
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ))
+    }
+    /// Find inode under current inode by name, following symlinks (relative
+    /// to this directory) up to [`SYMLINK_MAX_DEPTH`] hops.
+    ///
+    /// Requires execute (search) permission on this directory for the caller
+    /// `(uid, gids)`; returns `None` when it is denied.
+    pub fn find(&self, name: &str, uid: u32, gids: &[u32]) -> Option<Arc<Inode>> {
         let fs = self.fs.lock();
+        if !self.read_disk_inode(|d| self.check_access_pri(d, uid, gids, 0o1)) {
+            return None;
+        }
+        let mut target = self
+            .read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode))
+            .map(|inode_id| self.inode_at(inode_id, &fs))?;
+        for _ in 0..Self::SYMLINK_MAX_DEPTH {
+            if !target.is_symlink_pri() {
+                return Some(target);
+            }
+            let link = target.read_symlink_target();
+            match self.read_disk_inode(|disk_inode| self.find_inode_id(&link, disk_inode)) {
+                Some(inode_id) => target = self.inode_at(inode_id, &fs),
+                None => return None,
+            }
+        }
+        // the last hop may land on a non-symlink; anything else is a loop
+        if target.is_symlink_pri() {
+            // too many levels of symbolic links
+            return None;
+        }
+        Some(target)
+    }
+    /// Read the stored target path of a symlink inode (no fs lock).
+    fn read_symlink_target(&self) -> String {
         self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
+            let size = disk_inode.size as usize;
+            let mut buf = alloc::vec![0u8; size];
+            disk_inode.read_at(0, &mut buf, &self.block_device);
+            String::from_utf8_lossy(&buf).into_owned()
         })
     }
     /// Increase the size of a disk inode
@@ -148,7 +514,7 @@ impl Inode {
         let exist_count = (self.size() as usize) / DIRENT_SZ;
         let mut dirent = DirEntry::empty();
         let new_dirent = DirEntry::new(name, inode_id);
-        let mut flag = false;
+        let mut written_idx = None;
         for i in 0..exist_count {
             let offset = i * DIRENT_SZ;
             self.read_disk_inode(|disk_inode| {
@@ -158,20 +524,23 @@ impl Inode {
                 self.modify_disk_inode(|disk_inode| {
                     assert_eq!(disk_inode.write_at(offset, new_dirent.as_bytes(), &self.block_device), DIRENT_SZ);
                 });
-                flag = true;
+                written_idx = Some(i);
                 break;
             }
         }
-        
-        if !flag {
+
+        let written_idx = written_idx.unwrap_or_else(|| {
             let new_size = (exist_count + 1) * DIRENT_SZ;
             self.modify_disk_inode(|disk_inode| {
                 self.increase_size(new_size as u32, disk_inode, fs);
                 let offset = exist_count * DIRENT_SZ;
                 assert_eq!(disk_inode.write_at(offset, new_dirent.as_bytes(), &self.block_device), DIRENT_SZ);
             });
-        }
-        
+            exist_count
+        });
+        // keep the hash index in step with the authoritative dirent array
+        self.bucket_insert(name, written_idx as u32, fs);
+
         let inode = {
             let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id as u32);
             Arc::new(Self::new(
@@ -182,6 +551,8 @@ impl Inode {
             ))
         };
         inode.increase_link();
+        // 链接数变化属于状态变更，更新 ctime
+        inode.touch(false, false, true);
     }
     /// remove a dirent in dir
     fn remove_dirent(&self, dirent_idx: usize, fs: &mut MutexGuard<EasyFileSystem>) {
@@ -196,6 +567,9 @@ impl Inode {
             let illegal_dirent = DirEntry::new("has been removed", -1);
             disk_inode.write_at(dirent_idx * DIRENT_SZ, illegal_dirent.as_bytes(), &self.block_device);
         });
+        // tombstoned slots must leave the bucket so a stale hash can't shadow
+        // a later reuse of the name
+        self.bucket_purge(dirent.name(), dirent_idx as u32, fs);
 
         let inode_id = dirent.inode_id();
         let inode = {
@@ -207,6 +581,10 @@ impl Inode {
                 self.block_device.clone(),
             ))
         };
+        // 若文件在本次 unlink 后仍有链接，更新其 ctime（若降到 0 会被释放）
+        if inode.link_cnt() > 1 {
+            inode.touch(false, false, true);
+        }
         inode.decrease_link(fs);
     }
     /// Create inode under current inode by name
@@ -230,6 +608,10 @@ impl Inode {
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
                 new_inode.initialize(new_inode_id, DiskInodeType::File);
+                let t = now();
+                new_inode.atime = t;
+                new_inode.mtime = t;
+                new_inode.ctime = t;
             });
         self.append_dirent(name, new_inode_id as i32, &mut fs);
 
@@ -244,9 +626,158 @@ impl Inode {
         )))
         // release efs lock automatically by compiler
     }
-    /// List inodes under current inode
-    pub fn ls(&self) -> Vec<String> {
+    /// Create a symbolic link `name` whose contents are the path `target`.
+    pub fn symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let op = |root_inode: &DiskInode| {
+            assert!(root_inode.is_dir());
+            self.find_inode_id(name, root_inode)
+        };
+        if self.read_disk_inode(op).is_some() {
+            return None;
+        }
+        let new_inode_id = fs.alloc_inode();
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(new_inode_id, DiskInodeType::Symlink);
+                let t = now();
+                new_inode.atime = t;
+                new_inode.mtime = t;
+                new_inode.ctime = t;
+            });
+        self.append_dirent(name, new_inode_id as i32, &mut fs);
+        let new_inode = self.inode_at(new_inode_id, &fs);
+        // store the target path as the inode's data
+        new_inode.modify_disk_inode(|disk_inode| {
+            self.increase_size(target.len() as u32, disk_inode, &mut fs);
+            disk_inode.write_at(0, target.as_bytes(), &self.block_device);
+        });
+        block_cache_sync_all();
+        Some(new_inode)
+    }
+    /// Read back the target path of a symbolic link.
+    pub fn readlink(&self) -> Option<String> {
+        let _fs = self.fs.lock();
+        if !self.is_symlink_pri() {
+            return None;
+        }
+        Some(self.read_symlink_target())
+    }
+    /// is symlink? public
+    pub fn is_symlink(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.is_symlink_pri()
+    }
+    /// Create a subdirectory `name` under current inode.
+    ///
+    /// The new directory is initialized with `"."` (pointing at itself) and
+    /// `".."` (pointing at this parent) as its first two entries; that gives
+    /// it two links, and the parent's link count rises by one for the `".."`.
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let op = |root_inode: &DiskInode| {
+            assert!(root_inode.is_dir());
+            self.find_inode_id(name, root_inode)
+        };
+        if self.read_disk_inode(op).is_some() {
+            return None;
+        }
+        // alloc and initialize a directory inode
+        let new_inode_id = fs.alloc_inode();
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(new_inode_id, DiskInodeType::Dir);
+                let t = now();
+                new_inode.atime = t;
+                new_inode.mtime = t;
+                new_inode.ctime = t;
+            });
+        // link the new directory into this parent
+        self.append_dirent(name, new_inode_id as i32, &mut fs);
+        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        let new_inode = Arc::new(Self::new(
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ));
+        // "." points at itself, ".." points at the parent
+        new_inode.append_dirent(".", new_inode_id as i32, &mut fs);
+        new_inode.append_dirent("..", self.id() as i32, &mut fs);
+        block_cache_sync_all();
+        Some(new_inode)
+    }
+    /// Remove the empty subdirectory `name` under current inode.
+    ///
+    /// Fails (returns `-1`) if `name` does not name a directory, or if that
+    /// directory still holds entries other than `"."`/`".."`. On success the
+    /// parent loses the link held by the child's `".."`, and the directory's
+    /// blocks are freed through the normal link-count path.
+    pub fn rmdir(&self, name: &str) -> isize {
+        assert!(self.is_dir_pri());
+        let mut fs = self.fs.lock();
+        let exist_count = self.size() as usize / DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        let dirent_id = self.read_disk_inode(|disk_inode| {
+            for i in 0..exist_count {
+                let offset = i * DIRENT_SZ;
+                assert_eq!(disk_inode.read_at(offset, dirent.as_bytes_mut(), &self.block_device), DIRENT_SZ);
+                if dirent.is_valid() && dirent.name() == name {
+                    return i as isize;
+                }
+            }
+            -1
+        });
+        if dirent_id == -1 {
+            return -1;
+        }
+        let child_id = dirent.inode_id();
+        let (child_block_id, child_block_offset) = fs.get_disk_inode_pos(child_id as u32);
+        let child = Arc::new(Self::new(
+            child_block_id,
+            child_block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ));
+        // refuse non-directories and non-empty directories
+        let removable = child.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return false;
+            }
+            let count = disk_inode.size as usize / DIRENT_SZ;
+            let mut e = DirEntry::empty();
+            for i in 0..count {
+                disk_inode.read_at(i * DIRENT_SZ, e.as_bytes_mut(), &self.block_device);
+                if e.is_valid() && e.name() != "." && e.name() != ".." {
+                    return false;
+                }
+            }
+            true
+        });
+        if !removable {
+            return -1;
+        }
+        // drop the parent entry (child 2 -> 1), then the ".." parent link,
+        // then the "." self link which frees the directory's blocks.
+        self.remove_dirent(dirent_id as usize, &mut fs);
+        self.modify_disk_inode(|disk_inode| disk_inode.link_cnt -= 1);
+        child.decrease_link(&mut fs);
+        block_cache_sync_all();
+        0
+    }
+    /// List inodes under current inode.
+    ///
+    /// Requires read permission on this directory for `(uid, gids)`; returns
+    /// an empty listing when it is denied.
+    pub fn ls(&self, uid: u32, gids: &[u32]) -> Vec<String> {
         let _fs = self.fs.lock();
+        if !self.read_disk_inode(|d| self.check_access_pri(d, uid, gids, 0o4)) {
+            return Vec::new();
+        }
         self.read_disk_inode(|disk_inode| {
             let file_count = (disk_inode.size as usize) / DIRENT_SZ;
             let mut v: Vec<String> = Vec::new();
@@ -261,18 +792,36 @@ impl Inode {
             v
         })
     }
-    /// Read data from current inode
-    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+    /// Read data from current inode.
+    ///
+    /// Requires read permission for `(uid, gids)`; returns `0` when denied.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8], uid: u32, gids: &[u32]) -> usize {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        if !self.read_disk_inode(|d| self.check_access_pri(d, uid, gids, 0o4)) {
+            return 0;
+        }
+        let size = self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device));
+        self.touch(true, false, false);
+        size
     }
-    /// Write data to current inode
-    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+    /// Write data to current inode.
+    ///
+    /// Requires write permission for `(uid, gids)`; returns `0` when denied.
+    pub fn write_at(&self, offset: usize, buf: &[u8], uid: u32, gids: &[u32]) -> usize {
         let mut fs = self.fs.lock();
+        if !self.read_disk_inode(|d| self.check_access_pri(d, uid, gids, 0o2)) {
+            return 0;
+        }
         let size = self.modify_disk_inode(|disk_inode| {
             self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
+            // 非属主写入时清除 SUID/SGID 位，防止通过改写一个 setuid 文件的
+            // 内容来提权；属主与 root 改写自己的文件时保留这些位。
+            if uid != 0 && uid != disk_inode.uid {
+                disk_inode.mode &= !0o6000;
+            }
             disk_inode.write_at(offset, buf, &self.block_device)
         });
+        self.touch(false, true, true);
         block_cache_sync_all();
         size
     }
@@ -287,6 +836,7 @@ impl Inode {
                 fs.dealloc_data(data_block);
             }
         });
+        self.touch(false, true, true);
         block_cache_sync_all();
     }
     /// 将 new_name 也链接到 old_name 对应的 inode
@@ -335,6 +885,107 @@ impl Inode {
             -1
         }
     }
+    /// Read the dirent at slot `idx`.
+    fn read_dirent(&self, idx: usize) -> DirEntry {
+        let mut dirent = DirEntry::empty();
+        self.read_disk_inode(|disk_inode| {
+            disk_inode.read_at(idx * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+        });
+        dirent
+    }
+    /// Overwrite the dirent at slot `idx`.
+    fn write_dirent(&self, idx: usize, dirent: &DirEntry) {
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.write_at(idx * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+        });
+    }
+    /// Find the slot index of the valid entry `name`, with its inode id.
+    fn lookup_dirent(&self, name: &str) -> Option<(usize, i32)> {
+        let count = self.size() as usize / DIRENT_SZ;
+        for i in 0..count {
+            let dirent = self.read_dirent(i);
+            if dirent.is_valid() && dirent.name() == name {
+                return Some((i, dirent.inode_id()));
+            }
+        }
+        None
+    }
+    /// Repoint a moved subdirectory's ".." at `new_parent`.
+    fn fixup_dotdot(&self, child_id: i32, new_parent: i32, fs: &MutexGuard<EasyFileSystem>) {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(child_id as u32);
+        let child = Self::new(block_id, block_offset, self.fs.clone(), self.block_device.clone());
+        if !child.read_disk_inode(|d| d.is_dir()) {
+            return;
+        }
+        if let Some((idx, _)) = child.lookup_dirent("..") {
+            child.write_dirent(idx, &DirEntry::new("..", new_parent));
+        }
+    }
+    /// Atomically move entry `old_name` here to `new_name` in `new_dir`.
+    ///
+    /// With no flags an existing `new_name` is unlinked and the entry
+    /// re-pointed; `RENAME_NOREPLACE` fails if `new_name` exists;
+    /// `RENAME_EXCHANGE` swaps the two names' target inodes without touching
+    /// link counts. Both directories share one `Arc<Mutex<EasyFileSystem>>`,
+    /// so the fs lock is taken once and the dirent regions are edited
+    /// directly.
+    pub fn rename(
+        &self,
+        old_name: &str,
+        new_dir: &Arc<Inode>,
+        new_name: &str,
+        flags: u32,
+    ) -> isize {
+        assert!(self.is_dir_pri() && new_dir.is_dir_pri());
+        let mut fs = self.fs.lock();
+        let old = match self.lookup_dirent(old_name) {
+            Some(v) => v,
+            None => return -1,
+        };
+        let new = new_dir.lookup_dirent(new_name);
+
+        if flags & RENAME_EXCHANGE != 0 {
+            // both names must exist; swap their target inode ids in place
+            let new = match new {
+                Some(v) => v,
+                None => return -1,
+            };
+            self.write_dirent(old.0, &DirEntry::new(old_name, new.1));
+            new_dir.write_dirent(new.0, &DirEntry::new(new_name, old.1));
+            if self.id() != new_dir.id() {
+                self.fixup_dotdot(new.1, self.id() as i32, &fs);
+                new_dir.fixup_dotdot(old.1, new_dir.id() as i32, &fs);
+            }
+            block_cache_sync_all();
+            return 0;
+        }
+
+        if new.is_some() && flags & RENAME_NOREPLACE != 0 {
+            return -1;
+        }
+        // drop the victim at the destination, if any
+        if let Some((new_idx, _)) = new {
+            new_dir.remove_dirent(new_idx, &mut fs);
+        }
+        // link at the destination then unlink at the source (net link zero)
+        new_dir.append_dirent(new_name, old.1, &mut fs);
+        self.remove_dirent(old.0, &mut fs);
+        // a directory that changed parent needs its ".." and both parents' links fixed
+        if self.id() != new_dir.id() {
+            self.fixup_dotdot(old.1, new_dir.id() as i32, &fs);
+            let moved_is_dir = {
+                let (bid, boff) = fs.get_disk_inode_pos(old.1 as u32);
+                Self::new(bid, boff, self.fs.clone(), self.block_device.clone())
+                    .read_disk_inode(|d| d.is_dir())
+            };
+            if moved_is_dir {
+                self.modify_disk_inode(|d| d.link_cnt -= 1);
+                new_dir.modify_disk_inode(|d| d.link_cnt += 1);
+            }
+        }
+        block_cache_sync_all();
+        0
+    }
     /// get inode_id public
     pub fn get_id(&self) -> u32 {
         let _fs = self.fs.lock();
@@ -345,7 +996,16 @@ impl Inode {
         let _fs = self.fs.lock();
         self.is_dir_pri()
     }
-    /// get inode_id public 
+    /// Report filesystem usage (blocks/inodes, free counts, block size).
+    ///
+    /// The free counts are maintained incrementally by the block and inode
+    /// allocators, so this is a cheap read under the fs lock rather than a
+    /// walk of every inode.
+    pub fn stat_fs(&self) -> StatFs {
+        let fs = self.fs.lock();
+        fs.stat_fs()
+    }
+    /// get link count public
     pub fn nlink(&self) -> u32 {
         let _fs = self.fs.lock();
         self.link_cnt() as u32
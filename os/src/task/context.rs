@@ -0,0 +1,36 @@
+//! Task context switched by `__switch`.
+
+use crate::trap::trap_return;
+
+/// Callee-saved registers preserved across a context switch.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct TaskContext {
+    /// return address (`ra`) resumed by `__switch`
+    ra: usize,
+    /// kernel stack pointer (`sp`)
+    sp: usize,
+    /// callee-saved registers s0..s11
+    s: [usize; 12],
+}
+
+impl TaskContext {
+    /// A zeroed context, used for the per-hart idle control flow.
+    pub fn zero_init() -> Self {
+        Self {
+            ra: 0,
+            sp: 0,
+            s: [0; 12],
+        }
+    }
+    /// A context that, when switched to, drops into `trap_return` on the
+    /// kernel stack top `kstack_ptr` — i.e. returns a freshly created thread
+    /// to user space.
+    pub fn goto_trap_return(kstack_ptr: usize) -> Self {
+        Self {
+            ra: trap_return as usize,
+            sp: kstack_ptr,
+            s: [0; 12],
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Per-thread user resources and id allocation.
+//!
+//! A process owns one [`MemorySet`](crate::mm::MemorySet); every thread in it
+//! is distinguished by a thread id (tid). From the tid we derive that
+//! thread's user-stack region and its trap-context page, map them into the
+//! shared address space on allocation, and unmap exactly those areas on
+//! dealloc. A per-process [`RecycleAllocator`] hands out tids with a
+//! free list so exited threads' tids are reused.
+
+use super::ProcessControlBlock;
+use crate::config::{PAGE_SIZE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
+use crate::mm::{MapPermission, PhysPageNum, VirtAddr};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+/// A monotonically increasing id allocator with a recycled-id free list.
+pub struct RecycleAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl RecycleAllocator {
+    /// Create an empty allocator
+    pub fn new() -> Self {
+        RecycleAllocator {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    /// Allocate an id, reusing a recycled one when available
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
+        } else {
+            self.current += 1;
+            self.current - 1
+        }
+    }
+    /// Return an id to the free list
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        assert!(
+            !self.recycled.iter().any(|i| *i == id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
+}
+
+/// Bottom address of the trap-context page for thread `tid`.
+fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT_BASE - tid * PAGE_SIZE
+}
+
+/// Bottom address of thread `tid`'s user stack, laid out above the process's
+/// `ustack_base`.
+///
+/// Successive threads' stacks are stacked upward from the base, each
+/// separated from the next by one guard page so a stack overflow faults
+/// instead of corrupting the neighbouring thread.
+fn ustack_bottom_from_base(ustack_base: usize, tid: usize) -> usize {
+    ustack_base + tid * (USER_STACK_SIZE + PAGE_SIZE)
+}
+
+/// The user-space resources (stack + trap context) owned by one thread.
+pub struct TaskUserRes {
+    /// thread id within the owning process
+    pub tid: usize,
+    /// bottom of this thread's user stack
+    pub ustack_base: usize,
+    /// the process whose address space these resources live in
+    pub process: Weak<ProcessControlBlock>,
+}
+
+impl TaskUserRes {
+    /// Allocate a tid and, when `alloc_user_res` is set, map this thread's
+    /// stack and trap-context page into the process address space.
+    pub fn new(
+        process: Arc<ProcessControlBlock>,
+        ustack_base: usize,
+        alloc_user_res: bool,
+    ) -> Self {
+        let tid = process.inner_exclusive_access().alloc_tid();
+        let task_user_res = Self {
+            tid,
+            ustack_base,
+            process: Arc::downgrade(&process),
+        };
+        if alloc_user_res {
+            task_user_res.alloc_user_res();
+        }
+        task_user_res
+    }
+
+    /// Map this thread's user stack and trap-context page into the shared
+    /// address space.
+    pub fn alloc_user_res(&self) {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        // user stack
+        let ustack_bottom = ustack_bottom_from_base(self.ustack_base, self.tid);
+        let ustack_top = ustack_bottom + USER_STACK_SIZE;
+        process_inner.memory_set.insert_framed_area(
+            ustack_bottom.into(),
+            ustack_top.into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+        // trap context
+        let trap_cx_bottom = trap_cx_bottom_from_tid(self.tid);
+        let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
+        process_inner.memory_set.insert_framed_area(
+            trap_cx_bottom.into(),
+            trap_cx_top.into(),
+            MapPermission::R | MapPermission::W,
+        );
+    }
+
+    /// Unmap exactly the areas mapped by [`alloc_user_res`](Self::alloc_user_res).
+    fn dealloc_user_res(&self) {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        // user stack
+        let ustack_bottom_va: VirtAddr = ustack_bottom_from_base(self.ustack_base, self.tid).into();
+        process_inner
+            .memory_set
+            .remove_area_with_start_vpn(ustack_bottom_va.into());
+        // trap context
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
+        process_inner
+            .memory_set
+            .remove_area_with_start_vpn(trap_cx_bottom_va.into());
+    }
+
+    /// Return the tid to the process allocator.
+    fn dealloc_tid(&self) {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.dealloc_tid(self.tid);
+    }
+
+    /// Physical page of this thread's trap context, resolved through the
+    /// process page table.
+    pub fn trap_cx_ppn(&self) -> PhysPageNum {
+        let process = self.process.upgrade().unwrap();
+        let process_inner = process.inner_exclusive_access();
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
+        process_inner
+            .memory_set
+            .translate(trap_cx_bottom_va.into())
+            .unwrap()
+            .ppn()
+    }
+
+    /// User virtual address of this thread's trap context.
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_bottom_from_tid(self.tid)
+    }
+
+    /// Top of this thread's user stack.
+    pub fn ustack_top(&self) -> usize {
+        ustack_bottom_from_base(self.ustack_base, self.tid) + USER_STACK_SIZE
+    }
+}
+
+impl Drop for TaskUserRes {
+    fn drop(&mut self) {
+        self.dealloc_tid();
+        self.dealloc_user_res();
+    }
+}
@@ -0,0 +1,99 @@
+//! Ready queue and the stride scheduler.
+//!
+//! All runnable threads live in a single global ready queue behind
+//! [`TASK_MANAGER`]. [`fetch_task`] is the scheduler: it picks the ready
+//! thread with the smallest `stride` (pass counter), so higher-priority
+//! threads — whose pass `BIG_STRIDE / prior` is smaller — are chosen more
+//! often. Strides are compared through the signed difference of their wrapped
+//! subtraction so the fixed-width counter wraps around correctly; this is
+//! valid while every pass stays `<= BIG_STRIDE / 2`, i.e. `prior >= 2`.
+
+use super::{ProcessControlBlock, TaskControlBlock};
+use crate::sync::UPSafeCell;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// The ready queue of runnable threads.
+pub struct TaskManager {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl TaskManager {
+    /// Create an empty ready queue.
+    pub fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    /// Enqueue a runnable thread.
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    /// Remove and return the ready thread with the smallest stride.
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        if self.ready_queue.is_empty() {
+            return None;
+        }
+        let mut best = 0;
+        let mut best_stride = self.ready_queue[0].inner_exclusive_access().stride;
+        for i in 1..self.ready_queue.len() {
+            let stride = self.ready_queue[i].inner_exclusive_access().stride;
+            if stride_less(stride, best_stride) {
+                best = i;
+                best_stride = stride;
+            }
+        }
+        self.ready_queue.remove(best)
+    }
+}
+
+/// Compare two strides under wraparound, returning `true` when `a` logically
+/// precedes `b`. Treating `a < b` as the sign of the wrapped subtraction is
+/// correct while `STRIDE_MAX - STRIDE_MIN <= usize::MAX / 2`, which every pass
+/// being `<= BIG_STRIDE / 2` (i.e. `prior >= 2`) guarantees.
+fn stride_less(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+lazy_static! {
+    /// The global ready queue shared by every hart.
+    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
+        unsafe { UPSafeCell::new(TaskManager::new()) };
+}
+
+/// Add a thread to the ready queue.
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.exclusive_access().add(task);
+}
+
+/// Fetch the minimum-stride ready thread to run next.
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.exclusive_access().fetch()
+}
+
+lazy_static! {
+    /// Global pid -> process map, used by `waitpid` and friends to find a
+    /// process by its pid. A process is inserted when created and removed
+    /// when its main thread exits.
+    pub static ref PID2PCB: UPSafeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Look up a live process by pid.
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PID2PCB.exclusive_access().get(&pid).map(Arc::clone)
+}
+
+/// Register a process under its pid.
+pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
+    PID2PCB.exclusive_access().insert(pid, process);
+}
+
+/// Drop a process from the pid map when its main thread exits.
+pub fn remove_from_pid2process(pid: usize) {
+    let mut map = PID2PCB.exclusive_access();
+    if map.remove(&pid).is_none() {
+        panic!("cannot find pid {} in pid2process!", pid);
+    }
+}
@@ -3,352 +3,241 @@
 //! Everything about task management, like starting and switching tasks is
 //! implemented here.
 //!
-//! A single global instance of [`TaskManager`] called `TASK_MANAGER` controls
-//! all the tasks in the operating system.
+//! Scheduling is stride-based over a single global ready queue of threads
+//! (see [`manager`]); each hart runs its own idle control flow in
+//! [`processor`] and pulls the minimum-stride thread with `fetch_task`.
 //!
 //! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
 //! might not be what you expect.
 
 mod context;
+mod id;
+mod manager;
+mod pid;
+mod process;
+mod processor;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
-use crate::loader::{get_app_data, get_num_app};
-use crate::sync::UPSafeCell;
-use crate::trap::TrapContext;
-use crate::timer::get_time_ms;
-use crate::config::MAX_SYSCALL_NUM;
-use crate::syscall::{SYSCALL_TONG, TaskInfo};
-use crate::mm::{MapPermission, VPNRange, VirtAddr, VirtPageNum};
+use crate::loader::get_app_data_by_name;
+use crate::mm::{kernel_token, translated_byte_buffer, MapPermission, VPNRange, VirtAddr, VirtPageNum};
+use crate::trap::{trap_handler, TrapContext};
+use core::mem::size_of;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lazy_static::*;
-use switch::__switch;
-pub use task::{TaskControlBlock, TaskStatus};
 
 pub use context::TaskContext;
+pub use id::{RecycleAllocator, TaskUserRes};
+pub use manager::{
+    add_task, fetch_task, insert_into_pid2process, pid2process, remove_from_pid2process,
+};
+pub use pid::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
+pub use process::ProcessControlBlock;
+pub use processor::{
+    add_current_syscall_cnt, current_task, current_trap_cx, current_user_token, curr_set_priority,
+    get_current_info, hart_id, run_tasks, schedule, take_current_task,
+};
+pub use switch::__switch;
+pub use task::{TaskControlBlock, TaskStatus};
 
-/// The task manager, where all the tasks are managed.
-///
-/// Functions implemented on `TaskManager` deals with all task state transitions
-/// and task context switching. For convenience, you can find wrappers around it
-/// in the module level.
-///
-/// Most of `TaskManager` are hidden behind the field `inner`, to defer
-/// borrowing checks to runtime. You can see examples on how to use `inner` in
-/// existing functions on `TaskManager`.
-pub struct TaskManager {
-    /// total number of tasks
-    num_app: usize,
-    /// use inner value to get mutable access
-    inner: UPSafeCell<TaskManagerInner>,
-}
-
-/// The task manager inner in 'UPSafeCell'
-struct TaskManagerInner {
-    /// task list
-    tasks: Vec<TaskControlBlock>,
-    /// id of current `Running` task
-    current_task: usize,
-}
-
-lazy_static! {
-    /// a `TaskManager` global instance through lazy_static!
-    pub static ref TASK_MANAGER: TaskManager = {
-        println!("init TASK_MANAGER");
-        let num_app = get_num_app();
-        println!("num_app = {}", num_app);
-        let mut tasks: Vec<TaskControlBlock> = Vec::new();
-        for i in 0..num_app {
-            tasks.push(TaskControlBlock::new(get_app_data(i), i));
-        }
-        TaskManager {
-            num_app,
-            inner: unsafe {
-                UPSafeCell::new(TaskManagerInner {
-                    tasks,
-                    current_task: 0,
-                })
-            },
-        }
-    };
-}
-
-impl TaskManager {
-    /// Run the first task in task list.
-    ///
-    /// Generally, the first task in task list is an idle task (we call it zero process later).
-    /// But in ch4, we load apps statically, so the first task is a real app.
-    fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let task0 = &mut inner.tasks[0];
-        task0.task_status = TaskStatus::Running;
-        let next_task_cx_ptr = &task0.task_cx as *const TaskContext;
-
-        if task0.start_time == -1 {
-            task0.start_time = get_time_ms() as isize;
-        } else {
-            panic!("task0 is running");
-        }
-        
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        // before this, we should drop local variables that must be dropped manually
-        unsafe {
-            __switch(&mut _unused as *mut _, next_task_cx_ptr);
-        }
-        panic!("unreachable in run_first_task!");
-    }
-
-    /// Change the status of current `Running` task into `Ready`.
-    fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Ready;
-    }
-
-    /// Change the status of current `Running` task into `Exited`.
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Exited;
-    }
-
-    /// Find next task to run and return task id.
-    ///
-    /// In this case, we only return the first `Ready` task in task list.
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        (current + 1..current + self.num_app + 1)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
-    }
-
-    /// Get the current 'Running' task's token.
-    fn get_current_token(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_user_token()
-    }
-
-    /// Get the current 'Running' task's trap contexts.
-    fn get_current_trap_cx(&self) -> &'static mut TrapContext {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_trap_cx()
-    }
-
-    /// Change the current 'Running' task's program break
-    pub fn change_current_program_brk(&self, size: i32) -> Option<usize> {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].change_program_brk(size)
-    }
-
-    /// Switch current `Running` task to the task we have found,
-    /// or there is no `Ready` task and we can exit with all applications completed
-    fn run_next_task(&self) {
-        if let Some(next) = self.find_next_task() {
-            let mut inner = self.inner.exclusive_access();
-            let current = inner.current_task;
-            inner.tasks[next].task_status = TaskStatus::Running;
-
-            if inner.tasks[next].start_time == -1 {
-                inner.tasks[next].start_time = get_time_ms() as isize;
-            }
-            
-            inner.current_task = next;
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
-            drop(inner);
-            // before this, we should drop local variables that must be dropped manually
-            unsafe {
-                __switch(current_task_cx_ptr, next_task_cx_ptr);
-            }
-            // go back to user mode
-        } else {
-            panic!("All applications completed!");
-        }
-    }
-
-    /// add syscall cnt of current task
-    fn add_current_syscall_cnt(&self, syscall_id: usize) {
-        if let Some((id, _)) = SYSCALL_TONG
-            .iter()
-            .enumerate()
-            .find(|(_, &val)| syscall_id == val) 
+/// Suspend the current thread and schedule the next ready one.
+pub fn suspend_current_and_run_next() {
+    // take the current task off the processor
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    // put it back at the tail of the ready queue
+    add_task(task);
+    // jump to the idle control flow to pick the next thread
+    schedule(task_cx_ptr);
+}
+
+/// Exit the current thread. If it is the main thread of its process the whole
+/// process exits: it is marked a zombie, its children are reparented to the
+/// init process, and its per-thread resources are released.
+pub fn exit_current_and_run_next(exit_code: i32) {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let process = task.process.upgrade().unwrap();
+    let tid = task_inner.res.as_ref().unwrap().tid;
+    task_inner.exit_code = Some(exit_code);
+    // drop the exiting thread's user resources now
+    task_inner.res = None;
+    drop(task_inner);
+    drop(task);
+
+    // the main thread (tid 0) exiting tears down the whole process
+    if tid == 0 {
+        let pid = process.getpid();
+        remove_from_pid2process(pid);
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.is_zombie = true;
+        process_inner.exit_code = exit_code;
+
+        // reparent the dying process's children to the init process
         {
-            let mut inner = self.inner.exclusive_access();
-            let curr_id = inner.current_task;
-            let curr_task = &mut inner.tasks[curr_id];
-            curr_task.syscall_times[id] += 1;
-        } else {
-            panic!("Unsupported syscall_id: {}", syscall_id);
-        }
-    }
-
-    /// get information of current task
-    fn get_current_info(&self, ti: *mut TaskInfo) {
-        let inner = self.inner.exclusive_access();
-        let curr_task = &inner.tasks[inner.current_task];
-        let status = curr_task.task_status;
-        let mut syscall_times = [0; MAX_SYSCALL_NUM];
-        curr_task.syscall_times.iter().enumerate().for_each(|(id, cnt)| {
-            let syscall_id = SYSCALL_TONG[id];
-            syscall_times[syscall_id] = *cnt;
-        });
-        let time = get_time_ms() - curr_task.start_time as usize;
-        unsafe{
-            *ti = TaskInfo {
-                status,
-                syscall_times,
-                time
-            };
-        }
-    }
-
-    /// check whether a vpn has been mapped in vpnrange
-    pub fn curr_vpnrange_exist_map(&self, start:VirtPageNum, end: VirtPageNum) -> bool {
-        let inner = self.inner.exclusive_access();
-        let curr_task = &inner.tasks[inner.current_task];
-        
-        let vpnrange = VPNRange::new(start, end);
-        for vpn in vpnrange {
-            if curr_task.memory_set.vpn_ismap(vpn) {
-                return true;
+            let mut initproc_inner = INITPROC.inner_exclusive_access();
+            for child in process_inner.children.iter() {
+                child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+                initproc_inner.children.push(Arc::clone(child));
             }
         }
-        return false;
-    }
-
-    /// check whether a vpn has been unmapped in vpnrange
-    pub fn curr_vpnrange_exist_unmap(&self, start:VirtPageNum, end: VirtPageNum) -> bool {
-        let vpnrange = VPNRange::new(start, end);
-        let inner = self.inner.exclusive_access();
-        let curr_task = &inner.tasks[inner.current_task];
-        
-        for vpn in vpnrange {
-            if !curr_task.memory_set.vpn_ismap(vpn) {
-                return true;
+        process_inner.children.clear();
+        // Take each thread's user resources out under the process lock, but
+        // drop them only after releasing it: `TaskUserRes::drop` re-acquires
+        // `process_inner` to unmap the stack/trap page, which would otherwise
+        // be a re-entrant borrow.
+        let mut recycle_res = Vec::<TaskUserRes>::new();
+        for task in process_inner.tasks.iter().filter(|t| t.is_some()) {
+            let task = task.as_ref().unwrap();
+            let mut task_inner = task.inner_exclusive_access();
+            if let Some(res) = task_inner.res.take() {
+                recycle_res.push(res);
             }
         }
-        return false;
-    }
-
-    /// new a new area that is [start_va, end_va]
-    pub fn curr_mmap(
-        &self,
-        start_va: VirtAddr,
-        end_va: VirtAddr,
-        permission: MapPermission,
-    ) {
-        let mut inner = self.inner.exclusive_access();
-        let curr_id = inner.current_task;
-        let curr_task = &mut inner.tasks[curr_id];
-        curr_task.memory_set.insert_framed_area(start_va, end_va, permission);
-    }
-
-    /// unmap [start_va, end_va]
-    pub fn curr_munmap_with_start_vpn(&self, start_vpn: VirtPageNum) -> isize {
-        let mut inner = self.inner.exclusive_access();
-        let curr_id = inner.current_task;
-        let curr_task = &mut inner.tasks[curr_id];
-        curr_task.memory_set.remove_area_with_start_vpn(start_vpn)
+        drop(process_inner);
+        recycle_res.clear();
+        // reclaim the address space and forget the threads
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.memory_set.recycle_data_pages();
+        process_inner.tasks.clear();
     }
+    // no task context to save on the way out
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
 }
 
-/// Run the first task in task list.
-pub fn run_first_task() {
-    TASK_MANAGER.run_first_task();
-}
-
-/// Switch current `Running` task to the task we have found,
-/// or there is no `Ready` task and we can exit with all applications completed
-fn run_next_task() {
-    TASK_MANAGER.run_next_task();
-}
-
-/// Change the status of current `Running` task into `Ready`.
-fn mark_current_suspended() {
-    TASK_MANAGER.mark_current_suspended();
-}
-
-/// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
-}
-
-/// Suspend the current 'Running' task and run the next task in task list.
-pub fn suspend_current_and_run_next() {
-    mark_current_suspended();
-    run_next_task();
-}
-
-/// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
-    run_next_task();
-}
-
-/// Get the current 'Running' task's token.
-pub fn current_user_token() -> usize {
-    TASK_MANAGER.get_current_token()
-}
-
-/// Get the current 'Running' task's trap contexts.
-pub fn current_trap_cx() -> &'static mut TrapContext {
-    TASK_MANAGER.get_current_trap_cx()
-}
-
-/// Change the current 'Running' task's program break
-pub fn change_program_brk(size: i32) -> Option<usize> {
-    TASK_MANAGER.change_current_program_brk(size)
-}
-
-/// Add syscall times of current 'Running' task
-pub fn add_current_syscall_cnt(syscall_id: usize) {
-    TASK_MANAGER.add_current_syscall_cnt(syscall_id);
+lazy_static! {
+    /// The init process, ancestor of every other process and the reaper of
+    /// orphaned children.
+    pub static ref INITPROC: Arc<ProcessControlBlock> = {
+        let elf_data = get_app_data_by_name("initproc").unwrap();
+        ProcessControlBlock::new(elf_data)
+    };
 }
 
-/// Get info of current task
-pub fn get_current_info(ti: *mut TaskInfo) {
-    TASK_MANAGER.get_current_info(ti);
+/// Force the init process to be created and its main thread enqueued.
+pub fn add_initproc() {
+    let _initproc = INITPROC.clone();
+}
+
+/// Spawn a new thread in the current process, entering `entry` with argument
+/// `arg`, and return its tid.
+pub fn thread_create(entry: usize, arg: usize) -> usize {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let ustack_base = task
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .ustack_base;
+    // the new thread shares the process address space
+    let new_task = Arc::new(TaskControlBlock::new(Arc::clone(&process), ustack_base, true));
+    let new_task_inner = new_task.inner_exclusive_access();
+    let new_task_res = new_task_inner.res.as_ref().unwrap();
+    let new_task_tid = new_task_res.tid;
+    let ustack_top = new_task_res.ustack_top();
+    let mut process_inner = process.inner_exclusive_access();
+    // slot the thread into the process table, growing it as needed
+    while process_inner.tasks.len() < new_task_tid + 1 {
+        process_inner.tasks.push(None);
+    }
+    process_inner.tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    // enter `entry` with `arg` in a0
+    let trap_cx = new_task_inner.get_trap_cx();
+    *trap_cx = TrapContext::app_init_context(
+        entry,
+        ustack_top,
+        kernel_token(),
+        new_task.kstack.get_top(),
+        trap_handler as usize,
+    );
+    trap_cx.x[10] = arg;
+    drop(process_inner);
+    drop(new_task_inner);
+    // only now that the trap context is fully built is it safe for another
+    // hart to schedule the thread
+    add_task(Arc::clone(&new_task));
+    new_task_tid
+}
+
+/// Copy a kernel-built struct into a user pointer through the page table,
+/// tolerating a destination that straddles two non-contiguous physical
+/// frames. The struct is flattened into bytes and written segment by
+/// segment into the buffers returned by `translated_byte_buffer`, advancing
+/// the source pointer by each segment's length.
+pub(crate) fn write_user_struct<T>(token: usize, ptr: *mut T, value: &T) {
+    let buffers = translated_byte_buffer(token, ptr as *const u8, size_of::<T>());
+    let mut src = value as *const T as *const u8;
+    for buffer in buffers {
+        unsafe {
+            src.copy_to(buffer.as_mut_ptr(), buffer.len());
+            src = src.add(buffer.len());
+        }
+    }
 }
 
-/// check whether a vpn has been mapped in vpnrange of current task
-pub fn curr_vpnrange_exist_map(start: VirtPageNum, end: VirtPageNum) -> bool {
-    TASK_MANAGER.curr_vpnrange_exist_map(start, end)
+/// Whether any page in `[start, end)` is already mapped in the current
+/// process's address space.
+fn curr_vpnrange_exist_map(start: VirtPageNum, end: VirtPageNum) -> bool {
+    let process = current_task().unwrap().process.upgrade().unwrap();
+    let inner = process.inner_exclusive_access();
+    VPNRange::new(start, end)
+        .into_iter()
+        .any(|vpn| inner.memory_set.vpn_ismap(vpn))
 }
 
-/// check whether a vpn has been unmapped in vpnrange of current task
-pub fn curr_vpnrange_exist_unmap(start: VirtPageNum, end: VirtPageNum) -> bool {
-    TASK_MANAGER.curr_vpnrange_exist_unmap(start, end)
+/// Whether any page in `[start, end)` is currently unmapped in the current
+/// process's address space.
+fn curr_vpnrange_exist_unmap(start: VirtPageNum, end: VirtPageNum) -> bool {
+    let process = current_task().unwrap().process.upgrade().unwrap();
+    let inner = process.inner_exclusive_access();
+    VPNRange::new(start, end)
+        .into_iter()
+        .any(|vpn| !inner.memory_set.vpn_ismap(vpn))
 }
 
-/// alloc a new area that is [start_va, end_va]
+/// Map a new anonymous area `[start, start + len)` with `port` permissions
+/// into the current process.
 pub fn curr_mmap(start: usize, len: usize, mut port: usize) -> isize {
     let start_va = VirtAddr::from(start);
     let end_va = VirtAddr::from(start + len);
-    // println!("start: {:x}, end: {:x}", start_va.0, end_va.0);
     if !start_va.aligned()
-    || (port & !0x7) != 0 || (port & 0x7) == 0 
-    || curr_vpnrange_exist_map(start_va.floor(), end_va.ceil()) {
+        || (port & !0x7) != 0
+        || (port & 0x7) == 0
+        || curr_vpnrange_exist_map(start_va.floor(), end_va.ceil())
+    {
         -1
     } else {
         port <<= 1;
         let mut permission = MapPermission::from_bits(port as u8).unwrap();
-        permission = permission | MapPermission::U;
-        TASK_MANAGER.curr_mmap(start_va, end_va, permission);
+        permission |= MapPermission::U;
+        let process = current_task().unwrap().process.upgrade().unwrap();
+        process
+            .inner_exclusive_access()
+            .memory_set
+            .insert_framed_area(start_va, end_va, permission);
         0
     }
 }
 
-/// unmap a area that is starting in start_va
+/// Unmap the area of the current process starting at `start`.
 pub fn curr_munmap(start: usize, len: usize) -> isize {
     let start_va = VirtAddr::from(start);
     let end_va = VirtAddr::from(start + len);
     if !start_va.aligned() || curr_vpnrange_exist_unmap(start_va.floor(), end_va.ceil()) {
         -1
     } else {
-        TASK_MANAGER.curr_munmap_with_start_vpn(start_va.floor())
+        let process = current_task().unwrap().process.upgrade().unwrap();
+        let ret = process
+            .inner_exclusive_access()
+            .memory_set
+            .remove_area_with_start_vpn(start_va.floor());
+        ret
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,137 @@
+//! PID and kernel-stack allocation for the process subsystem.
+//!
+//! Every process gets a unique PID from [`PID_ALLOCATOR`]. Each thread gets
+//! its own kernel stack, mapped high in the kernel address space and
+//! positioned by an independent id from [`KSTACK_ALLOCATOR`] (threads of one
+//! process must not share a kernel stack). Both resources are reclaimed
+//! through `Drop`: dropping a [`TaskControlBlock`] releases its kernel stack,
+//! and dropping the collected zombie's `ProcessControlBlock` releases its PID.
+
+use super::id::RecycleAllocator;
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
+use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// A recycling allocator of monotonically increasing ids with a free list.
+struct PidAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl PidAllocator {
+    /// Create an empty allocator
+    pub fn new() -> Self {
+        PidAllocator {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    /// Allocate a PID, reusing a recycled one when available
+    pub fn alloc(&mut self) -> PidHandle {
+        if let Some(pid) = self.recycled.pop() {
+            PidHandle(pid)
+        } else {
+            self.current += 1;
+            PidHandle(self.current - 1)
+        }
+    }
+    /// Return a PID to the free list
+    pub fn dealloc(&mut self, pid: usize) {
+        assert!(pid < self.current);
+        assert!(
+            !self.recycled.iter().any(|ppid| *ppid == pid),
+            "pid {} has been deallocated!",
+            pid
+        );
+        self.recycled.push(pid);
+    }
+}
+
+lazy_static! {
+    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
+        unsafe { UPSafeCell::new(PidAllocator::new()) };
+}
+
+/// Bind a PID lifetime to an RAII handle so it is recycled on drop.
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// Allocate a fresh PID from the global allocator
+pub fn pid_alloc() -> PidHandle {
+    PID_ALLOCATOR.exclusive_access().alloc()
+}
+
+lazy_static! {
+    /// Allocator of kernel-stack ids, one per live thread. Kept separate from
+    /// the PID allocator so each thread of a multi-threaded process gets a
+    /// distinct kernel stack.
+    static ref KSTACK_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+}
+
+/// Return the `[bottom, top)` of the kernel stack with the given id.
+///
+/// Stacks are stacked downward from the trampoline, each separated from the
+/// next by one guard page.
+pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
+    let top = TRAMPOLINE - kstack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let bottom = top - KERNEL_STACK_SIZE;
+    (bottom, top)
+}
+
+/// Allocate a fresh kernel stack and map it into `KERNEL_SPACE`.
+pub fn kstack_alloc() -> KernelStack {
+    let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
+    let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(kstack_id);
+    KERNEL_SPACE.exclusive_access().insert_framed_area(
+        kernel_stack_bottom.into(),
+        kernel_stack_top.into(),
+        MapPermission::R | MapPermission::W,
+    );
+    KernelStack { kstack_id }
+}
+
+/// Kernel stack of a thread, keyed by a kstack id and mapped into
+/// `KERNEL_SPACE`.
+pub struct KernelStack {
+    kstack_id: usize,
+}
+
+impl KernelStack {
+    /// Push a value onto the top of the stack and return its address.
+    #[allow(unused)]
+    pub fn push_on_top<T>(&self, value: T) -> *mut T
+    where
+        T: Sized,
+    {
+        let kernel_stack_top = self.get_top();
+        let ptr_mut = (kernel_stack_top - core::mem::size_of::<T>()) as *mut T;
+        unsafe {
+            *ptr_mut = value;
+        }
+        ptr_mut
+    }
+    /// Top address of the kernel stack.
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.kstack_id);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.kstack_id);
+        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+        KERNEL_SPACE
+            .exclusive_access()
+            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.exclusive_access().dealloc(self.kstack_id);
+    }
+}
@@ -0,0 +1,222 @@
+//! Process control block and the fork/exec/waitpid subsystem.
+//!
+//! A [`ProcessControlBlock`] owns the address space shared by all of its
+//! threads, the parent/child tree used by `waitpid`, and the per-process tid
+//! allocator. Threads themselves are [`TaskControlBlock`]s kept in `tasks`;
+//! the main thread is created together with the process. Every live process
+//! is registered in the global pid -> process map so `waitpid` and signals can
+//! find it by pid.
+
+use super::id::RecycleAllocator;
+use super::manager::insert_into_pid2process;
+use super::{add_task, pid_alloc, PidHandle, TaskControlBlock};
+use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+/// A process: an address space plus the threads running in it.
+pub struct ProcessControlBlock {
+    /// the process id, reclaimed on drop
+    pub pid: PidHandle,
+    /// mutable state behind a `UPSafeCell`
+    inner: UPSafeCell<ProcessControlBlockInner>,
+}
+
+/// Mutable per-process state.
+pub struct ProcessControlBlockInner {
+    /// set once the process has exited and is awaiting collection
+    pub is_zombie: bool,
+    /// the address space shared by every thread of this process
+    pub memory_set: MemorySet,
+    /// the parent process, if any (weak so it does not keep the parent alive)
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// child processes, kept alive until collected by `waitpid`
+    pub children: Vec<Arc<ProcessControlBlock>>,
+    /// exit code set by `exit`, read back by the parent's `waitpid`
+    pub exit_code: i32,
+    /// threads of this process, indexed by tid (holes are exited threads)
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    /// per-process tid allocator with a recycled-id free list
+    pub task_res_allocator: RecycleAllocator,
+}
+
+impl ProcessControlBlockInner {
+    /// Token of this process's page table.
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    /// Allocate a fresh tid for a new thread.
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+    /// Return a tid to the free list.
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+    /// Number of thread slots (including tombstones of exited threads).
+    pub fn thread_count(&self) -> usize {
+        self.tasks.len()
+    }
+    /// Borrow thread `tid`, which must still be live.
+    pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
+        self.tasks[tid].as_ref().unwrap().clone()
+    }
+}
+
+impl ProcessControlBlock {
+    /// Exclusive access to the inner state.
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// This process's pid.
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+    /// Build a brand-new process from an ELF image, with one main thread.
+    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let pid_handle = pid_alloc();
+        let process = Arc::new(Self {
+            pid: pid_handle,
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    parent: None,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    tasks: Vec::new(),
+                    task_res_allocator: RecycleAllocator::new(),
+                })
+            },
+        });
+        // create the main thread, mapping its user resources
+        let task = Arc::new(TaskControlBlock::new(
+            Arc::clone(&process),
+            ustack_base,
+            true,
+        ));
+        // initialize its trap context to enter the ELF entry point
+        let task_inner = task.inner_exclusive_access();
+        let trap_cx = task_inner.get_trap_cx();
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        let kstack_top = task.kstack.get_top();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.exclusive_access().token(),
+            kstack_top,
+            trap_handler as usize,
+        );
+        drop(task_inner);
+        // register the main thread with the process and the scheduler
+        process.inner_exclusive_access().tasks.push(Some(Arc::clone(&task)));
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
+        add_task(task);
+        process
+    }
+    /// Replace this (single-threaded) process's image with a new ELF, pushing
+    /// `args` onto the rebuilt user stack.
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
+        assert_eq!(self.inner_exclusive_access().thread_count(), 1);
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let new_token = memory_set.token();
+        // install the new address space
+        self.inner_exclusive_access().memory_set = memory_set;
+        // the main thread re-derives its user resources in the new space
+        let task = self.inner_exclusive_access().get_task(0);
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
+        task_inner.res.as_mut().unwrap().alloc_user_res();
+        task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
+        // push argv onto the user stack
+        let mut user_sp = task_inner.res.as_mut().unwrap().ustack_top();
+        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
+        let argv_base = user_sp;
+        let mut argv: Vec<_> = (0..=args.len())
+            .map(|arg| {
+                translated_refmut(
+                    new_token,
+                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
+                )
+            })
+            .collect();
+        *argv[args.len()] = 0;
+        for i in 0..args.len() {
+            user_sp -= args[i].len() + 1;
+            *argv[i] = user_sp;
+            let mut p = user_sp;
+            for c in args[i].as_bytes() {
+                *translated_refmut(new_token, p as *mut u8) = *c;
+                p += 1;
+            }
+            *translated_refmut(new_token, p as *mut u8) = 0;
+        }
+        // align the stack pointer to 8 bytes for k210
+        user_sp -= user_sp % core::mem::size_of::<usize>();
+        // rebuild the trap context for the new entry point
+        let mut trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            task.kstack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
+        *task_inner.get_trap_cx() = trap_cx;
+    }
+    /// Fork this (single-threaded) process, deep-copying its address space.
+    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+        let mut parent = self.inner_exclusive_access();
+        assert_eq!(parent.thread_count(), 1);
+        // copy-on-nothing: fully duplicate the parent's address space
+        let memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        let pid = pid_alloc();
+        let child = Arc::new(Self {
+            pid,
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    tasks: Vec::new(),
+                    task_res_allocator: RecycleAllocator::new(),
+                })
+            },
+        });
+        parent.children.push(Arc::clone(&child));
+        // the child inherits the parent main thread's user resources in-place,
+        // so it must not re-map them (alloc_user_res == false)
+        let ustack_base = parent
+            .get_task(0)
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .ustack_base;
+        let task = Arc::new(TaskControlBlock::new(
+            Arc::clone(&child),
+            ustack_base,
+            false,
+        ));
+        child.inner_exclusive_access().tasks.push(Some(Arc::clone(&task)));
+        // the child's trap context lives at the same user va as the parent's,
+        // already copied by the address-space duplication
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.trap_cx_ppn = task_inner.res.as_ref().unwrap().trap_cx_ppn();
+        // the kernel stack differs, so fix up its pointer in the trap context
+        task_inner.get_trap_cx().kernel_sp = task.kstack.get_top();
+        drop(task_inner);
+        drop(parent);
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        add_task(task);
+        child
+    }
+}
@@ -7,14 +7,15 @@
 use super::__switch;
 use super::{fetch_task, TaskStatus};
 use super::{TaskContext, TaskControlBlock};
-use crate::mm::{MapPermission, VirtAddr};
 use crate::sync::UPSafeCell;
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 use crate::timer::get_time_ms;
-use crate::config::{BIG_STRIDE, MAX_SYSCALL_NUM};
+use crate::config::{BIG_STRIDE, MAX_HARTS, MAX_SYSCALL_NUM};
 use crate::syscall::{TaskInfo, SYSCALL_TONG};
+use core::arch::asm;
 
 /// Processor management structure
 pub struct Processor {
@@ -51,14 +52,38 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// One [`Processor`] per hart, indexed by `hart_id`. Each hart owns its
+    /// own `idle_task_cx` and `current`; they share the ready queue behind
+    /// `fetch_task`.
+    pub static ref PROCESSORS: Vec<UPSafeCell<Processor>> = {
+        let mut v = Vec::with_capacity(MAX_HARTS);
+        for _ in 0..MAX_HARTS {
+            v.push(unsafe { UPSafeCell::new(Processor::new()) });
+        }
+        v
+    };
+}
+
+/// Read the id of the calling hart from the thread pointer (`tp`), which is
+/// set to the hart id at boot.
+pub fn hart_id() -> usize {
+    let tp: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) tp);
+    }
+    tp
+}
+
+/// Borrow the calling hart's [`Processor`].
+fn current_processor() -> &'static UPSafeCell<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 ///The main part of process execution and scheduling
 ///Loop `fetch_task` to get the process that needs to run, and switch the process through `__switch`
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = current_processor().exclusive_access();
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
@@ -71,8 +96,9 @@ pub fn run_tasks() {
                 task_inner.start_time = get_time_ms() as isize;
             }
 
-            // 增加当前运行进程的步长
-            task_inner.stride += BIG_STRIDE / task_inner.prior;
+            // 增加当前运行进程的步长；用 wrapping_add 让步长计数器按设计回绕，
+            // 与 manager 里基于 wrapping_sub 的比较保持一致。
+            task_inner.stride = task_inner.stride.wrapping_add(BIG_STRIDE / task_inner.prior);
 
             // release coming task_inner manually
             drop(task_inner);
@@ -91,16 +117,16 @@ pub fn run_tasks() {
 
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    current_processor().exclusive_access().take_current()
 }
 
 pub fn restore_current_task(curr_task: Arc<TaskControlBlock>) {
-    PROCESSOR.exclusive_access().current = Some(curr_task);
+    current_processor().exclusive_access().current = Some(curr_task);
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    current_processor().exclusive_access().current()
 }
 
 /// Get the current user token(addr of page table)
@@ -119,7 +145,7 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
 
 ///Return to idle control flow for new scheduling
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    let mut processor = current_processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
     unsafe {
@@ -157,27 +183,29 @@ pub fn get_current_info(ti: *mut TaskInfo) {
         syscall_times[syscall_id] = *cnt;
     });
     let time = get_time_ms() - inner.start_time as usize;
-    unsafe{
-        *ti = TaskInfo {
-            status,
-            syscall_times,
-            time
-        };
-    }
+    drop(inner);
+    let token = curr_task.get_user_token();
+    // 用户指针不能在内核地址空间直接解引用，且 TaskInfo 可能跨页，
+    // 先在内核栈上构造再按页分段写回。
+    let info = TaskInfo {
+        status,
+        syscall_times,
+        time,
+    };
+    super::write_user_struct(token, ti, &info);
 }
 
 /// 修改当前运行进程的优先级
-pub fn curr_set_priority(prio: isize) {
+///
+/// 步长调度要求优先级不小于 2（保证每次 pass `<= BIG_STRIDE / 2`），
+/// 低于 2 的优先级被拒绝，返回 -1。
+pub fn curr_set_priority(prio: isize) -> isize {
+    if prio < 2 {
+        return -1;
+    }
     let curr_task = take_current_task().unwrap();
     let mut inner = curr_task.inner_exclusive_access();
     inner.prior = prio as usize;
     restore_current_task(curr_task.clone());
-}
-
-/// 给当前进程新增加一块内存映射 [start_va, end_va)
-pub fn curr_mmap(start_va: VirtAddr, end_va: VirtAddr, permission: MapPermission) {
-    let curr_task = take_current_task().unwrap();
-    let mut inner = curr_task.inner_exclusive_access();
-    inner.memory_set.insert_framed_area(start_va, end_va, permission);
-    restore_current_task(curr_task.clone());
+    prio
 }
\ No newline at end of file
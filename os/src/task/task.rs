@@ -0,0 +1,107 @@
+//! The thread control block.
+//!
+//! A [`TaskControlBlock`] is a single thread of a [`ProcessControlBlock`]: it
+//! borrows the process's address space (via a weak reference) and owns only
+//! the resources that are per-thread — a kernel stack, a [`TaskUserRes`]
+//! (user stack + trap-context page), the saved task context and the
+//! scheduling bookkeeping (stride/priority, start time, syscall counts).
+
+use super::id::TaskUserRes;
+use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
+use crate::config::MAX_SYSCALL_NUM;
+use crate::mm::PhysPageNum;
+use crate::sync::UPSafeCell;
+use crate::trap::TrapContext;
+use alloc::sync::{Arc, Weak};
+use core::cell::RefMut;
+
+/// A thread of execution within a process.
+pub struct TaskControlBlock {
+    /// the owning process (weak, so a thread never keeps its process alive)
+    pub process: Weak<ProcessControlBlock>,
+    /// this thread's kernel stack
+    pub kstack: KernelStack,
+    /// mutable state behind a `UPSafeCell`
+    inner: UPSafeCell<TaskControlBlockInner>,
+}
+
+/// Mutable per-thread state.
+pub struct TaskControlBlockInner {
+    /// user-space resources (stack + trap context), freed when the thread exits
+    pub res: Option<TaskUserRes>,
+    /// physical page of this thread's trap context
+    pub trap_cx_ppn: PhysPageNum,
+    /// saved kernel task context for `__switch`
+    pub task_cx: TaskContext,
+    /// scheduling status
+    pub task_status: TaskStatus,
+    /// exit code, set once the thread exits
+    pub exit_code: Option<i32>,
+    /// stride-scheduling pass counter
+    pub stride: usize,
+    /// scheduling priority (`>= 2`)
+    pub prior: usize,
+    /// first-run timestamp in ms, `-1` until scheduled
+    pub start_time: isize,
+    /// per-syscall invocation counts
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+}
+
+impl TaskControlBlockInner {
+    /// Mutable reference to this thread's trap context.
+    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
+        self.trap_cx_ppn.get_mut()
+    }
+}
+
+impl TaskControlBlock {
+    /// Exclusive access to the inner state.
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// Token of the owning process's page table.
+    pub fn get_user_token(&self) -> usize {
+        let process = self.process.upgrade().unwrap();
+        let inner = process.inner_exclusive_access();
+        inner.get_user_token()
+    }
+    /// Create a thread in `process`. When `alloc_user_res` is set its user
+    /// stack and trap-context page are mapped; otherwise (e.g. the child of a
+    /// fork) they are assumed to already exist in the address space.
+    pub fn new(process: Arc<ProcessControlBlock>, ustack_base: usize, alloc_user_res: bool) -> Self {
+        let res = TaskUserRes::new(Arc::clone(&process), ustack_base, alloc_user_res);
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let kstack = kstack_alloc();
+        let kstack_top = kstack.get_top();
+        Self {
+            process: Arc::downgrade(&process),
+            kstack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    res: Some(res),
+                    trap_cx_ppn,
+                    task_cx: TaskContext::goto_trap_return(kstack_top),
+                    task_status: TaskStatus::Ready,
+                    exit_code: None,
+                    stride: 0,
+                    prior: 16,
+                    start_time: -1,
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                })
+            },
+        }
+    }
+}
+
+/// The running status of a thread.
+#[derive(Copy, Clone, PartialEq)]
+pub enum TaskStatus {
+    /// ready to run, waiting in the ready queue
+    Ready,
+    /// currently running on some hart
+    Running,
+    /// blocked on a resource
+    Blocked,
+    /// exited, awaiting collection
+    Exited,
+}